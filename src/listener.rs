@@ -0,0 +1,75 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+
+/// Any duplex byte stream a listener hands us, whether it came from a TCP or a Unix
+/// domain socket, so the accept loops and the tcpman request logic stay transport-agnostic.
+pub trait AsyncReadAndWrite: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncReadAndWrite for T {}
+
+/// Where a connection came from, for logging.
+pub enum PeerAddr {
+    Tcp(std::net::SocketAddr),
+    Unix(PathBuf),
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{addr}"),
+            PeerAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A listener bound to either a TCP address or a Unix domain socket path.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Binds `addr`. A `unix:` prefix binds a Unix domain socket at the given path
+    /// (replacing any existing socket file there); anything else is bound as a TCP address.
+    pub async fn bind(addr: &str) -> anyhow::Result<Self> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            let _ = std::fs::remove_file(path);
+            Ok(Listener::Unix(UnixListener::bind(path).with_context(|| format!("Binding unix socket {path}"))?))
+        } else {
+            Ok(Listener::Tcp(TcpListener::bind(addr).await.with_context(|| format!("Binding {addr}"))?))
+        }
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<String> {
+        match self {
+            Listener::Tcp(l) => Ok(l.local_addr().context("Getting local address")?.to_string()),
+            Listener::Unix(l) => Ok(l.local_addr()
+                .context("Getting local address")?
+                .as_pathname()
+                .map(|p| format!("unix:{}", p.display()))
+                .unwrap_or_else(|| "unix:<unnamed>".to_owned())),
+        }
+    }
+
+    /// Accepts a connection, along with the local address it was accepted on (`None` for a
+    /// Unix domain socket, which has no routable address a remote peer could be told to use).
+    pub async fn accept(&self) -> anyhow::Result<(Box<dyn AsyncReadAndWrite>, PeerAddr, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(l) => {
+                let (stream, addr) = l.accept().await.context("Accepting TCP connection")?;
+                let local_addr = stream.local_addr().ok();
+                Ok((Box::new(stream), PeerAddr::Tcp(addr), local_addr))
+            }
+
+            Listener::Unix(l) => {
+                let (stream, addr) = l.accept().await.context("Accepting unix connection")?;
+                let path = addr.as_pathname().map(PathBuf::from).unwrap_or_default();
+                Ok((Box::new(stream), PeerAddr::Unix(path), None))
+            }
+        }
+    }
+}
@@ -1,43 +1,206 @@
-use anyhow::{bail, Context};
+use std::fs::File;
+use std::io::{BufReader as StdBufReader, Cursor};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
 use async_shutdown::Shutdown;
-use tokio::io::{BufReader, copy_bidirectional};
-use tokio::net::{TcpListener, TcpStream};
+use clap::Parser;
+use rustls::ServerConfig;
+use rustls_pemfile::{certs, private_key};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket, lookup_host};
 use tokio::signal::ctrl_c;
 use tokio::spawn;
+use tokio_rustls::TlsAcceptor;
+
+use listener::Listener;
+use relay::{copy_bidirectional_with_idle_timeout, ConnectionRegistry};
+use tcpman::{BlankConnectionMessage, Request as TcpmanRequest};
+use tcpman::ws::WsStream;
 
+mod listener;
+mod relay;
 mod socks5;
 mod tcpman;
 
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Address to listen on for SOCKS5 clients. A `unix:` prefix binds a Unix domain
+    /// socket at that path instead.
+    #[arg(long, default_value = "[::0]:6000")]
+    socks_listen: String,
+
+    /// Address to listen on for tcpman tunnel clients. If unset, no tunnel server is started.
+    #[arg(long)]
+    tcpman_listen: Option<String>,
+
+    /// Path to a PEM-encoded TLS certificate chain for the tcpman tunnel server
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to the PEM-encoded private key matching `tls_cert`
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Address to listen on for tcpman tunnel clients carried over a WebSocket upgrade,
+    /// so the tunnel can pass through HTTP proxies, reverse proxies and CDNs.
+    #[arg(long)]
+    ws_listen: Option<String>,
+
+    /// Username:password pair required to use the SOCKS5 listener. May be given multiple
+    /// times. If none are given, the listener accepts unauthenticated connections, so only
+    /// bind it beyond localhost once this is set.
+    #[arg(long = "socks-credential", value_name = "USER:PASS")]
+    socks_credentials: Vec<String>,
+
+    /// Address to listen on for tcpman tunnel clients over QUIC. Requires `tls_cert`/`tls_key`,
+    /// since QUIC always runs over TLS 1.3.
+    #[arg(long, requires = "tls_cert")]
+    quic_listen: Option<String>,
+
+    /// How long a proxied TCP connection may go without any traffic in either direction
+    /// before it's forcibly closed, so a stalled peer can't hold it open forever.
+    #[arg(long, default_value = "300", value_name = "SECONDS")]
+    idle_timeout_secs: u64,
+
+    /// How long to let in-flight connections finish on their own once shutdown starts
+    /// before they're cancelled.
+    #[arg(long, default_value = "30", value_name = "SECONDS")]
+    drain_timeout_secs: u64,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let _ = dotenvy::dotenv();
     env_logger::init();
 
-    let listener = TcpListener::bind("[::0]:6000").await.context("To bind")?;
+    let cli = Cli::parse();
+
+    let listener = Listener::bind(&cli.socks_listen).await.context("To bind")?;
     log::info!("Listening on {}", listener.local_addr()?);
 
+    let socks5_config = build_socks5_config(&cli.socks_credentials).context("Parsing SOCKS5 credentials")?;
+
+    let idle_timeout = Duration::from_secs(cli.idle_timeout_secs);
+    let drain_timeout = Duration::from_secs(cli.drain_timeout_secs);
+    let registry = ConnectionRegistry::new();
+
     let shutdown = Shutdown::new();
 
-    spawn(shutdown.wrap_cancel(serve_socks5(shutdown.clone(), listener)));
+    spawn(shutdown.wrap_cancel(serve_socks5(shutdown.clone(), listener, socks5_config, idle_timeout, registry.clone())));
+
+    if let Some(addr) = &cli.tcpman_listen {
+        let listener = TcpListener::bind(addr).await.context("To bind tcpman listener")?;
+        log::info!("Listening for tcpman tunnels on {}", listener.local_addr()?);
+
+        let tls_acceptor = match (&cli.tls_cert, &cli.tls_key) {
+            (Some(cert), Some(key)) => Some(load_tls_acceptor(cert, key).context("Loading TLS certificate")?),
+            _ => None,
+        };
+
+        spawn(shutdown.wrap_cancel(serve_tcpman(shutdown.clone(), listener, tls_acceptor, idle_timeout, registry.clone())));
+    }
+
+    if let Some(addr) = &cli.ws_listen {
+        let listener = TcpListener::bind(addr).await.context("To bind tcpman websocket listener")?;
+        log::info!("Listening for tcpman websocket tunnels on {}", listener.local_addr()?);
+
+        spawn(shutdown.wrap_cancel(serve_tcpman_ws(shutdown.clone(), listener, idle_timeout, registry.clone())));
+    }
+
+    if let Some(addr) = &cli.quic_listen {
+        let (cert, key) = (cli.tls_cert.as_ref().unwrap(), cli.tls_key.as_ref().unwrap());
+        let quic_config = load_quic_server_config(cert, key).context("Loading QUIC server config")?;
+        let endpoint = quinn::Endpoint::server(quic_config, addr.parse().context("Parsing QUIC listen address")?)
+            .context("To bind tcpman QUIC listener")?;
+        log::info!("Listening for tcpman QUIC tunnels on {}", endpoint.local_addr()?);
+
+        spawn(shutdown.wrap_cancel(serve_tcpman_quic(shutdown.clone(), endpoint, idle_timeout, registry.clone())));
+    }
 
     ctrl_c().await.context("Waiting for Ctrl-C")?;
 
-    log::info!("Shutting down...");
+    log::info!("Shutting down, draining in-flight connections (up to {drain_timeout:?})...");
     shutdown.shutdown();
-    shutdown.wait_shutdown_complete().await;
-    log::info!("Shutdown complete");
+    if tokio::time::timeout(drain_timeout, shutdown.wait_shutdown_complete()).await.is_err() {
+        log::warn!("Drain timeout elapsed with connections still active, exiting anyway");
+    } else {
+        log::info!("Shutdown complete");
+    }
 
     Ok(())
 }
 
-async fn serve_socks5(shutdown: Shutdown, listener: TcpListener) -> anyhow::Result<()> {
+fn build_socks5_config(raw_credentials: &[String]) -> anyhow::Result<socks5::AcceptorConfig> {
+    if raw_credentials.is_empty() {
+        return Ok(socks5::AcceptorConfig::new());
+    }
+
+    let mut credentials = socks5::Credentials::new();
+    for entry in raw_credentials {
+        let (user, pass) = entry.split_once(':').with_context(|| format!("Invalid USER:PASS credential: {entry}"))?;
+        credentials = credentials.add(user, pass);
+    }
+
+    Ok(socks5::AcceptorConfig::new().with_credentials(credentials))
+}
+
+fn load_tls_cert_and_key(cert_path: &PathBuf, key_path: &PathBuf) -> anyhow::Result<(Vec<rustls::pki_types::CertificateDer<'static>>, rustls::pki_types::PrivateKeyDer<'static>)> {
+    let cert_chain = certs(&mut StdBufReader::new(File::open(cert_path).context("Opening TLS certificate")?))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Parsing TLS certificate")?;
+
+    let key = private_key(&mut StdBufReader::new(File::open(key_path).context("Opening TLS key")?))
+        .context("Parsing TLS key")?
+        .context("No private key found")?;
+
+    Ok((cert_chain, key))
+}
+
+fn load_tls_acceptor(cert_path: &PathBuf, key_path: &PathBuf) -> anyhow::Result<TlsAcceptor> {
+    let (cert_chain, key) = load_tls_cert_and_key(cert_path, key_path)?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .context("Building TLS server config")?;
+    config.alpn_protocols = vec![tcpman::ALPN_PROTOCOL.to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_quic_server_config(cert_path: &PathBuf, key_path: &PathBuf) -> anyhow::Result<quinn::ServerConfig> {
+    let (cert_chain, key) = load_tls_cert_and_key(cert_path, key_path)?;
+    let mut config = quinn::ServerConfig::with_single_cert(cert_chain, key).context("Building QUIC server config")?;
+
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(tcpman::quic::KEEP_ALIVE_INTERVAL));
+    config.transport_config(Arc::new(transport));
+
+    Ok(config)
+}
+
+async fn serve_socks5(shutdown: Shutdown, listener: Listener, config: socks5::AcceptorConfig, idle_timeout: Duration, registry: ConnectionRegistry) -> anyhow::Result<()> {
     while let Some(v) = shutdown.wrap_cancel(listener.accept()).await {
-        let (stream, addr) = v.context("Accepting connection")?;
+        let (stream, addr, local_addr) = v.context("Accepting connection")?;
         log::debug!("Accepted connection from {addr}");
 
-        let shutdown = shutdown.clone();
+        // Holding a delay token (rather than wrapping the handler itself in `wrap_cancel`)
+        // means a connection in flight when shutdown starts gets to finish on its own,
+        // bounded only by the drain timeout `main` applies around `wait_shutdown_complete`.
+        let Ok(delay_token) = shutdown.delay_shutdown_token() else {
+            log::debug!("Rejecting connection from {addr}: shutting down");
+            continue;
+        };
+        let config = config.clone();
+        let registry = registry.clone();
         spawn(async move {
-            if let Some(Err(e)) = shutdown.wrap_cancel(handle_socks5_client(stream)).await {
+            let _delay_token = delay_token;
+            let _guard = registry.track();
+            if let Err(e) = handle_socks5_client(stream, config, idle_timeout, registry.clone(), local_addr).await {
                 log::error!("Error handling connection from {addr}: {e:?}");
             }
             log::debug!("Disconnected: {addr}");
@@ -47,19 +210,40 @@ async fn serve_socks5(shutdown: Shutdown, listener: TcpListener) -> anyhow::Resu
     Ok(())
 }
 
-async fn handle_socks5_client(stream: TcpStream) -> anyhow::Result<()> {
+async fn handle_socks5_client(
+    stream: Box<dyn listener::AsyncReadAndWrite>,
+    config: socks5::AcceptorConfig,
+    idle_timeout: Duration,
+    registry: ConnectionRegistry,
+    local_addr: Option<SocketAddr>,
+) -> anyhow::Result<()> {
     use socks5::*;
 
-    let (req, acceptor) = Acceptor::accept(BufReader::new(stream)).await.context("Accepting socks5 connection")?;
+    let (req, acceptor) = Acceptor::accept(BufReader::new(stream), &config).await.context("Accepting socks5 connection")?;
     log::info!("Proxying {req:?}");
 
+    match &req {
+        Request::Connect(..) => handle_socks5_connect(req, acceptor, idle_timeout, registry).await,
+        Request::UdpAssociate(..) => handle_socks5_udp_associate(acceptor, local_addr, idle_timeout).await,
+        _ => bail!("Invalid request"),
+    }
+}
+
+async fn handle_socks5_connect(
+    req: socks5::Request<'_>,
+    acceptor: socks5::Acceptor<BufReader<Box<dyn listener::AsyncReadAndWrite>>>,
+    idle_timeout: Duration,
+    registry: ConnectionRegistry,
+) -> anyhow::Result<()> {
+    use socks5::*;
+
     let upstream = match &req {
         Request::Connect(Address::Domain(addr), port) => TcpStream::connect((addr.as_ref(), *port)).await,
         Request::Connect(Address::IP(addr), port) => TcpStream::connect((*addr, *port)).await,
         _ => bail!("Invalid request"),
     };
 
-    let (mut stream, mut upstream) = match upstream {
+    let (stream, upstream) = match upstream {
         Ok(upstream) => {
             let bound = upstream.local_addr().unwrap();
             log::info!("Connected to {req:?}");
@@ -72,7 +256,320 @@ async fn handle_socks5_client(stream: TcpStream) -> anyhow::Result<()> {
         }
     };
 
-    let (upload, download) = copy_bidirectional(&mut stream, &mut upstream).await.context("Copying data")?;
+    let (upload, download) = copy_bidirectional_with_idle_timeout(stream, upstream, idle_timeout).await.context("Copying data")?;
+    registry.record_bytes(upload, download);
     log::debug!("Disconnecting from {req:?}, uploaded {upload} bytes, downloaded {download} bytes");
     Ok(())
 }
+
+/// Relays UDP datagrams for a `UDP ASSOCIATE` request over a single local socket, using
+/// the address they arrive from to tell the SOCKS5 client's own datagrams (which carry a
+/// SOCKS5 UDP header and need relaying to their target) apart from a target's replies
+/// (which need the header re-added before being sent back to the client).
+///
+/// Targets are always dialed directly from here; tunneling this through a remote tcpman
+/// server (via `tcpman::client` and `Request::Udp`) is not implemented.
+async fn handle_socks5_udp_associate(
+    acceptor: socks5::Acceptor<BufReader<Box<dyn listener::AsyncReadAndWrite>>>,
+    local_addr: Option<SocketAddr>,
+    idle_timeout: Duration,
+) -> anyhow::Result<()> {
+    use socks5::*;
+
+    // Bind on the same interface the client's control connection arrived on, rather than the
+    // wildcard address, so the address we hand back in `reply_success` is one the client can
+    // actually reach (a `0.0.0.0`/`::` bind address is not a valid destination for a peer).
+    let local_addr = local_addr.ok_or_else(|| anyhow!("no routable local address for UDP associate on this listener"))?;
+
+    let relay = UdpSocket::bind(SocketAddr::new(local_addr.ip(), 0)).await.context("Binding UDP relay socket")?;
+    let bound = relay.local_addr().context("Getting UDP relay address")?;
+
+    let mut control = acceptor.reply_success(&Address::IP(bound.ip()), bound.port()).await.context("Replying to socks5 conn")?;
+    log::info!("UDP associate bound to {bound}");
+
+    let mut buf = vec![0u8; 65536];
+    let mut client_addr = None;
+
+    let idle = tokio::time::sleep(idle_timeout);
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            // The control connection closing (or erroring) tears down the association.
+            res = control.read_u8() => {
+                let _ = res;
+                break;
+            }
+
+            res = relay.recv_from(&mut buf) => {
+                let (n, from) = res.context("Receiving UDP datagram")?;
+                if let Err(e) = relay_socks5_udp_datagram(&relay, &mut client_addr, from, &buf[..n]).await {
+                    log::warn!("Dropping UDP datagram from {from}: {e:?}");
+                }
+                idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+            }
+
+            () = &mut idle => {
+                log::debug!("UDP association on {bound} idle for {idle_timeout:?}, tearing down");
+                break;
+            }
+        }
+    }
+
+    log::debug!("UDP association on {bound} torn down");
+    Ok(())
+}
+
+async fn relay_socks5_udp_datagram(
+    relay: &UdpSocket,
+    client_addr: &mut Option<SocketAddr>,
+    from: SocketAddr,
+    data: &[u8],
+) -> anyhow::Result<()> {
+    use socks5::*;
+
+    if client_addr.is_none() || *client_addr == Some(from) {
+        // A datagram from the SOCKS5 client: strip its header and forward the payload.
+        let mut cursor = Cursor::new(data);
+        let _reserved = cursor.read_u16().await.context("Reading reserved bytes")?;
+        let frag = cursor.read_u8().await.context("Reading FRAG")?;
+        if frag != 0 {
+            bail!("fragmented UDP datagrams are not supported");
+        }
+        let address = Address::parse(&mut cursor).await.context("Parsing address")?;
+        let port = cursor.read_u16().await.context("Reading port")?;
+        let payload = &data[cursor.position() as usize..];
+
+        let target = resolve_target(&address, port).await?;
+        relay.send_to(payload, target).await.context("Forwarding UDP datagram to target")?;
+        *client_addr = Some(from);
+    } else {
+        // A reply from a target: re-add the SOCKS5 UDP header and send it back to the client.
+        let client = client_addr.ok_or_else(|| anyhow!("no client address known yet"))?;
+
+        let mut out = Vec::with_capacity(data.len() + 24);
+        out.extend_from_slice(&[0, 0, 0]);
+        Address::IP(from.ip()).write(&mut out);
+        out.extend_from_slice(&from.port().to_be_bytes());
+        out.extend_from_slice(data);
+
+        relay.send_to(&out, client).await.context("Relaying UDP reply to client")?;
+    }
+
+    Ok(())
+}
+
+async fn resolve_target(address: &socks5::Address<'_>, port: u16) -> anyhow::Result<SocketAddr> {
+    match address {
+        socks5::Address::IP(ip) => Ok(SocketAddr::new(*ip, port)),
+        socks5::Address::Domain(domain) => lookup_host((domain.as_ref(), port))
+            .await
+            .context("Resolving domain")?
+            .next()
+            .ok_or_else(|| anyhow!("No address found for {domain}")),
+    }
+}
+
+async fn serve_tcpman(shutdown: Shutdown, listener: TcpListener, tls_acceptor: Option<TlsAcceptor>, idle_timeout: Duration, registry: ConnectionRegistry) -> anyhow::Result<()> {
+    while let Some(v) = shutdown.wrap_cancel(listener.accept()).await {
+        let (stream, addr) = v.context("Accepting tcpman connection")?;
+        log::debug!("Accepted tcpman connection from {addr}");
+
+        let Ok(delay_token) = shutdown.delay_shutdown_token() else {
+            log::debug!("Rejecting tcpman connection from {addr}: shutting down");
+            continue;
+        };
+        let tls_acceptor = tls_acceptor.clone();
+        let registry = registry.clone();
+        spawn(async move {
+            let _delay_token = delay_token;
+            let _guard = registry.track();
+
+            let result = match tls_acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await.context("Performing TLS handshake") {
+                    Ok(stream) => handle_tcpman_client(BufReader::new(stream), idle_timeout, registry.clone()).await,
+                    Err(e) => Err(e),
+                },
+                None => handle_tcpman_client(BufReader::new(stream), idle_timeout, registry.clone()).await,
+            };
+
+            if let Err(e) = result {
+                log::error!("Error handling tcpman connection from {addr}: {e:?}");
+            }
+            log::debug!("Disconnected: {addr}");
+        });
+    }
+
+    Ok(())
+}
+
+async fn serve_tcpman_ws(shutdown: Shutdown, listener: TcpListener, idle_timeout: Duration, registry: ConnectionRegistry) -> anyhow::Result<()> {
+    while let Some(v) = shutdown.wrap_cancel(listener.accept()).await {
+        let (stream, addr) = v.context("Accepting tcpman websocket connection")?;
+        log::debug!("Accepted tcpman websocket connection from {addr}");
+
+        let Ok(delay_token) = shutdown.delay_shutdown_token() else {
+            log::debug!("Rejecting tcpman websocket connection from {addr}: shutting down");
+            continue;
+        };
+        let registry = registry.clone();
+        spawn(async move {
+            let _delay_token = delay_token;
+            let _guard = registry.track();
+
+            let result = match async_tungstenite::tokio::accept_async(stream).await.context("Performing websocket handshake") {
+                Ok(ws) => handle_tcpman_client(BufReader::new(WsStream::new(ws)), idle_timeout, registry.clone()).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                log::error!("Error handling tcpman websocket connection from {addr}: {e:?}");
+            }
+            log::debug!("Disconnected: {addr}");
+        });
+    }
+
+    Ok(())
+}
+
+async fn serve_tcpman_quic(shutdown: Shutdown, endpoint: quinn::Endpoint, idle_timeout: Duration, registry: ConnectionRegistry) -> anyhow::Result<()> {
+    while let Some(connecting) = shutdown.wrap_cancel(endpoint.accept()).await.flatten() {
+        let Ok(delay_token) = shutdown.delay_shutdown_token() else {
+            continue;
+        };
+        let shutdown = shutdown.clone();
+        let registry = registry.clone();
+        spawn(async move {
+            let _delay_token = delay_token;
+
+            match connecting.await {
+                Ok(connection) => {
+                    let addr = connection.remote_address();
+                    log::debug!("Accepted tcpman QUIC connection from {addr}");
+
+                    if let Err(e) = serve_tcpman_quic_connection(shutdown.clone(), connection, idle_timeout, registry).await {
+                        log::error!("Error handling tcpman QUIC connection from {addr}: {e:?}");
+                    }
+                    log::debug!("Disconnected: {addr}");
+                }
+                Err(e) => log::error!("Error accepting tcpman QUIC connection: {e:?}"),
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn serve_tcpman_quic_connection(shutdown: Shutdown, connection: quinn::Connection, idle_timeout: Duration, registry: ConnectionRegistry) -> anyhow::Result<()> {
+    while let Some(accepted) = shutdown.wrap_cancel(connection.accept_bi()).await {
+        let (send, recv) = accepted.context("Accepting QUIC stream")?;
+
+        let Ok(delay_token) = shutdown.delay_shutdown_token() else {
+            continue;
+        };
+        let registry = registry.clone();
+        spawn(async move {
+            let _delay_token = delay_token;
+            let _guard = registry.track();
+
+            let stream = tcpman::quic::QuicStream::new(send, recv);
+            if let Err(e) = handle_tcpman_client(BufReader::new(stream), idle_timeout, registry.clone()).await {
+                log::error!("Error handling tcpman QUIC stream: {e:?}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+async fn handle_tcpman_client(
+    mut stream: impl tokio::io::AsyncBufRead + tokio::io::AsyncWrite + Unpin,
+    idle_timeout: Duration,
+    registry: ConnectionRegistry,
+) -> anyhow::Result<()> {
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let n = stream.read_line(&mut line).await.context("Reading tcpman message")?;
+        if n == 0 {
+            return Ok(());
+        }
+
+        match serde_json::from_str(&line).context("Parsing tcpman message")? {
+            BlankConnectionMessage::Ping => continue,
+            BlankConnectionMessage::Connect(req) => return serve_tcpman_request(stream, req, idle_timeout, registry).await,
+        }
+    }
+}
+
+async fn serve_tcpman_request(
+    stream: impl AsyncBufRead + AsyncWrite + Unpin,
+    req: TcpmanRequest<'_>,
+    idle_timeout: Duration,
+    registry: ConnectionRegistry,
+) -> anyhow::Result<()> {
+    match req {
+        TcpmanRequest::Tcp { addr, port, initial_data } => {
+            let mut upstream = TcpStream::connect((addr.as_ref(), port)).await.context("Connecting to upstream")?;
+            if let Some(initial_data) = &initial_data {
+                upstream.write_all(initial_data).await.context("Sending initial TCP data")?;
+            }
+
+            let (upload, download) = copy_bidirectional_with_idle_timeout(stream, upstream, idle_timeout).await.context("Copying data")?;
+            registry.record_bytes(upload, download);
+            log::debug!("Disconnecting from {addr}:{port}, uploaded {upload} bytes, downloaded {download} bytes");
+            Ok(())
+        }
+
+        TcpmanRequest::Udp { addr, port, initial_data } => {
+            let target = lookup_host((addr.as_ref(), port)).await.context("Resolving domain")?
+                .next().ok_or_else(|| anyhow!("No address found for {addr}"))?;
+
+            let udp = UdpSocket::bind("0.0.0.0:0").await.context("Binding UDP socket")?;
+            udp.connect(target).await.context("Connecting UDP socket")?;
+
+            if !initial_data.is_empty() {
+                udp.send(&initial_data).await.context("Sending initial UDP data")?;
+            }
+
+            relay_tcpman_udp(stream, &udp).await
+        }
+    }
+}
+
+/// Relays UDP datagrams for a single target between the tcpman tunnel stream and a
+/// connected `UdpSocket`, each datagram framed on the tunnel as a u16 big-endian length
+/// followed by its payload, since the tunnel is otherwise just a byte stream.
+async fn relay_tcpman_udp(mut stream: impl AsyncBufRead + AsyncWrite + Unpin, udp: &UdpSocket) -> anyhow::Result<()> {
+    let mut recv_buf = vec![0u8; 65536];
+    let mut upload = 0u64;
+    let mut download = 0u64;
+
+    loop {
+        tokio::select! {
+            len = stream.read_u16() => {
+                let len = match len {
+                    Ok(len) => len,
+                    Err(_) => break,
+                };
+
+                let mut payload = vec![0u8; len as usize];
+                stream.read_exact(&mut payload).await.context("Reading UDP frame")?;
+                udp.send(&payload).await.context("Forwarding UDP frame to target")?;
+                upload += payload.len() as u64;
+            }
+
+            res = udp.recv(&mut recv_buf) => {
+                let n = res.context("Receiving UDP reply from target")?;
+                stream.write_u16(n as u16).await.context("Writing UDP frame length")?;
+                stream.write_all(&recv_buf[..n]).await.context("Writing UDP frame")?;
+                stream.flush().await.context("Flushing UDP frame")?;
+                download += n as u64;
+            }
+        }
+    }
+
+    log::debug!("UDP tunnel closed, uploaded {upload} bytes, downloaded {download} bytes");
+    Ok(())
+}
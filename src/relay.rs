@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+/// Relays bytes bidirectionally between `a` and `b`, the same as `tokio::io::copy_bidirectional`,
+/// except the relay is aborted once `idle_timeout` passes with no traffic in either direction,
+/// so a stalled peer can't hold the task and the upstream socket open forever.
+pub async fn copy_bidirectional_with_idle_timeout<A, B>(mut a: A, mut b: B, idle_timeout: Duration) -> anyhow::Result<(u64, u64)>
+    where
+        A: AsyncRead + AsyncWrite + Unpin,
+        B: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf_a = vec![0u8; 8192];
+    let mut buf_b = vec![0u8; 8192];
+    let mut upload = 0u64;
+    let mut download = 0u64;
+    let mut a_done = false;
+    let mut b_done = false;
+
+    let idle = tokio::time::sleep(idle_timeout);
+    tokio::pin!(idle);
+
+    while !a_done || !b_done {
+        tokio::select! {
+            n = a.read(&mut buf_a), if !a_done => {
+                match n.context("Reading from downstream")? {
+                    0 => {
+                        a_done = true;
+                        let _ = b.shutdown().await;
+                    }
+                    n => {
+                        b.write_all(&buf_a[..n]).await.context("Writing to upstream")?;
+                        b.flush().await.context("Flushing upstream")?;
+                        upload += n as u64;
+                        idle.as_mut().reset(Instant::now() + idle_timeout);
+                    }
+                }
+            }
+
+            n = b.read(&mut buf_b), if !b_done => {
+                match n.context("Reading from upstream")? {
+                    0 => {
+                        b_done = true;
+                        let _ = a.shutdown().await;
+                    }
+                    n => {
+                        a.write_all(&buf_b[..n]).await.context("Writing to downstream")?;
+                        a.flush().await.context("Flushing downstream")?;
+                        download += n as u64;
+                        idle.as_mut().reset(Instant::now() + idle_timeout);
+                    }
+                }
+            }
+
+            () = &mut idle => {
+                bail!("Idle timeout after {idle_timeout:?} with no traffic in either direction");
+            }
+        }
+    }
+
+    Ok((upload, download))
+}
+
+/// Process-wide, lock-free counters for proxied connections, so a future status endpoint
+/// can report live connection counts and total bytes transferred.
+#[derive(Clone, Default)]
+pub struct ConnectionRegistry(Arc<Counters>);
+
+#[derive(Default)]
+struct Counters {
+    active_connections: AtomicUsize,
+    total_uploaded: AtomicU64,
+    total_downloaded: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionStats {
+    pub active_connections: usize,
+    pub total_uploaded: u64,
+    pub total_downloaded: u64,
+}
+
+/// Tracks one active connection for as long as it's held; dropping it (e.g. when the
+/// handler task ends) decrements the active connection count.
+pub struct ConnectionGuard(ConnectionRegistry);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&self) -> ConnectionGuard {
+        self.0.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self.clone())
+    }
+
+    pub fn record_bytes(&self, uploaded: u64, downloaded: u64) {
+        self.0.total_uploaded.fetch_add(uploaded, Ordering::Relaxed);
+        self.0.total_downloaded.fetch_add(downloaded, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            active_connections: self.0.active_connections.load(Ordering::Relaxed),
+            total_uploaded: self.0.total_uploaded.load(Ordering::Relaxed),
+            total_downloaded: self.0.total_downloaded.load(Ordering::Relaxed),
+        }
+    }
+}
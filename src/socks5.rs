@@ -1,18 +1,95 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use anyhow::{bail, Context};
 use bytes::BufMut;
 use smallvec::{smallvec, SmallVec};
+use subtle::ConstantTimeEq;
 use tokio::io::{AsyncWrite, AsyncWriteExt, AsyncBufRead, AsyncReadExt};
 use num_enum::IntoPrimitive;
 
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NO_ACCEPTABLE: u8 = 0xff;
+
+/// A set of username/password pairs accepted by [`Acceptor::accept`]'s RFC 1929
+/// sub-negotiation. Configuring credentials makes the listener require
+/// authentication, so it's safe to expose beyond localhost.
+#[derive(Clone, Debug, Default)]
+pub struct Credentials(HashMap<String, String>);
+
+impl Credentials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.0.insert(username.into(), password.into());
+        self
+    }
+
+    /// Looks up `username` and compares `password` in constant time, so a remote attacker
+    /// can't use response timing to learn how many leading bytes of the password matched.
+    fn validate(&self, username: &str, password: &str) -> bool {
+        match self.0.get(username) {
+            Some(expected) => expected.as_bytes().ct_eq(password.as_bytes()).into(),
+            None => false,
+        }
+    }
+}
+
+/// Configuration for [`Acceptor::accept`]. Defaults to no auth, matching plain SOCKS5.
+#[derive(Clone, Debug, Default)]
+pub struct AcceptorConfig {
+    credentials: Option<Credentials>,
+}
+
+impl AcceptorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires clients to authenticate with one of the given credentials instead of
+    /// allowing the no-auth method.
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+}
+
+/// Runs the RFC 1929 username/password sub-negotiation, closing the connection on failure.
+async fn authenticate(stream: &mut (impl AsyncBufRead + AsyncWrite + Unpin), credentials: &Credentials) -> anyhow::Result<()> {
+    if stream.read_u8().await.context("Reading auth version")? != 0x01 {
+        bail!("invalid username/password auth version");
+    }
+
+    let ulen = stream.read_u8().await.context("Reading username length")? as usize;
+    let mut username = vec![0u8; ulen];
+    stream.read_exact(&mut username).await.context("Reading username")?;
+
+    let plen = stream.read_u8().await.context("Reading password length")? as usize;
+    let mut password = vec![0u8; plen];
+    stream.read_exact(&mut password).await.context("Reading password")?;
+
+    let username = String::from_utf8(username).context("Decoding username")?;
+    let password = String::from_utf8(password).context("Decoding password")?;
+
+    if credentials.validate(&username, &password) {
+        stream.write_all(&[0x01, 0x00]).await.context("Writing auth success")?;
+        Ok(())
+    } else {
+        stream.write_all(&[0x01, 0x01]).await.context("Writing auth failure")?;
+        bail!("invalid username or password");
+    }
+}
+
 pub struct Acceptor<S> {
     stream: S,
     is_v6: bool,
 }
 
 impl<S> Acceptor<S> {
-    pub async fn accept(mut stream: S) -> anyhow::Result<(Request<'static>, Self)>
+    pub async fn accept(mut stream: S, config: &AcceptorConfig) -> anyhow::Result<(Request<'static>, Self)>
         where
             S: AsyncBufRead + AsyncWrite + Unpin,
     {
@@ -25,13 +102,24 @@ impl<S> Acceptor<S> {
         let mut auth_methods: SmallVec<[u8; 1]> = smallvec![0u8; n_auth];
         stream.read_exact(&mut auth_methods).await.context("Reading auth methods")?;
 
-        // Make sure the no auth is in the list
-        if !auth_methods.contains(&0x00) {
-            bail!("only no auth is supported");
-        }
+        let method = match &config.credentials {
+            Some(_) if auth_methods.contains(&METHOD_USERNAME_PASSWORD) => METHOD_USERNAME_PASSWORD,
+            Some(_) => {
+                stream.write_all(&[0x5, METHOD_NO_ACCEPTABLE]).await.context("Writing auth response")?;
+                bail!("client did not offer username/password authentication");
+            }
+            None if auth_methods.contains(&METHOD_NO_AUTH) => METHOD_NO_AUTH,
+            None => {
+                stream.write_all(&[0x5, METHOD_NO_ACCEPTABLE]).await.context("Writing auth response")?;
+                bail!("client did not offer no auth");
+            }
+        };
+
+        stream.write_all(&[0x5, method]).await.context("Writing auth response")?;
 
-        // Respond OK
-        stream.write_all(&[0x5, 0x0]).await.context("Writing auth response")?;
+        if method == METHOD_USERNAME_PASSWORD {
+            authenticate(&mut stream, config.credentials.as_ref().unwrap()).await?;
+        }
 
         // Read the request
         if stream.read_u8().await.context("Reading request SOCKS version")? != 0x05 {
@@ -78,7 +166,9 @@ impl<S> Acceptor<S> {
             Address::default(self.is_v6).write(&mut buf);
         }
 
-        self.stream.write_u16(port.unwrap_or(0)).await.context("Writing port")?;
+        buf.put_u16(port.unwrap_or(0));
+
+        self.stream.write_all(&buf).await.context("Writing socks5 reply")?;
         Ok(())
     }
 }
@@ -92,7 +182,7 @@ pub enum Address<'a> {
 
 impl<'a> Address<'a> {
 
-    fn write(&self, w: &mut impl BufMut) {
+    pub(crate) fn write(&self, w: &mut impl BufMut) {
         match self {
             Address::IP(IpAddr::V4(addr)) => {
                 w.put_u8(0x1);
@@ -122,7 +212,7 @@ impl Address<'static> {
         }
     }
 
-    async fn parse(s: &mut (impl AsyncBufRead + Unpin)) -> anyhow::Result<Self> {
+    pub(crate) async fn parse(s: &mut (impl AsyncBufRead + Unpin)) -> anyhow::Result<Self> {
         match s.read_u8().await.context("Reading address type")? {
             0x1 => {
                 let mut buf = [0u8; 4];
@@ -1,45 +1,145 @@
+use std::sync::Arc;
+
 use anyhow::Context;
-use clap::builder::Str;
-use tokio::io::{AsyncBufRead, AsyncWrite, BufReader};
-use tokio::net::{TcpStream, ToSocketAddrs};
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs, UnixStream};
+use tokio_rustls::{client::TlsStream, TlsConnector};
 
-use super::{BlankConnectionMessage, Request};
+use super::{BlankConnectionMessage, Request, ALPN_PROTOCOL};
+use super::ws::WsStream;
 
 pub struct BlankConnection<S> {
     stream: S,
     message_buf: Vec<u8>,
-};
+}
+
+impl<S> BlankConnection<S> {
+    fn new(stream: S) -> Self {
+        Self { stream, message_buf: Vec::new() }
+    }
+}
 
 impl BlankConnection<BufReader<TcpStream>> {
     pub async fn connect(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
-        todo!()
+        let stream = TcpStream::connect(addr).await.context("Connecting to tcpman server")?;
+        Ok(Self::new(BufReader::new(stream)))
     }
 }
 
-impl<S> BlankConnection<S> {
-    pub async fn request<'a>(mut self, req: Request<'a>) -> anyhow::Result<EstablishedConnection<S>>
-        where S: AsyncBufRead + AsyncWrite + Unpin {
+impl BlankConnection<BufReader<UnixStream>> {
+    /// Connects to a tcpman server over a Unix domain socket, e.g. when it's chained
+    /// behind another local process or reached via systemd socket activation.
+    pub async fn connect_unix(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path).await.context("Connecting to tcpman server")?;
+        Ok(Self::new(BufReader::new(stream)))
+    }
+}
+
+impl BlankConnection<BufReader<TlsStream<TcpStream>>> {
+    /// Connects to a tcpman server and wraps the connection in TLS, advertising the
+    /// [`ALPN_PROTOCOL`] id so the server can distinguish tunnel clients from other
+    /// traffic it might be serving on the same port.
+    pub async fn connect_tls(
+        addr: impl ToSocketAddrs,
+        server_name: ServerName<'static>,
+        mut config: ClientConfig,
+    ) -> anyhow::Result<Self> {
+        config.alpn_protocols = vec![ALPN_PROTOCOL.to_vec()];
+
+        let stream = TcpStream::connect(addr).await.context("Connecting to tcpman server")?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+            .context("Performing TLS handshake")?;
+
+        Ok(Self::new(BufReader::new(stream)))
+    }
+}
+
+impl BlankConnection<BufReader<WsStream<TcpStream>>> {
+    /// Connects to a tcpman server through a WebSocket upgrade. `host` is sent as the
+    /// `Host` header (and may be a CDN's `BASE_DOMAIN` rather than `addr` itself), `path`
+    /// is the HTTP path of the upgrade request.
+    pub async fn connect_ws(addr: impl ToSocketAddrs, host: &str, path: &str) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(addr).await.context("Connecting to tcpman server")?;
+
+        let request = format!("ws://{host}{path}")
+            .into_client_request()
+            .context("Building websocket handshake request")?;
+
+        let (ws, _response) = async_tungstenite::tokio::client_async(request, stream)
+            .await
+            .context("Performing websocket handshake")?;
+
+        Ok(Self::new(BufReader::new(WsStream::new(ws))))
+    }
+}
+
+impl<S> BlankConnection<S>
+    where S: AsyncBufRead + AsyncWrite + Unpin
+{
+    pub async fn request<'a>(mut self, req: Request<'a>) -> anyhow::Result<EstablishedConnection<S>> {
         self.message_buf.clear();
         serde_json::to_writer(&mut self.message_buf, &BlankConnectionMessage::Connect(req)).context("writing json")?;
-        todo!()
+        self.message_buf.push(b'\n');
+
+        self.stream.write_all(&self.message_buf).await.context("Sending connect request")?;
+        self.stream.flush().await.context("Flushing connect request")?;
+
+        Ok(EstablishedConnection(self.stream))
     }
 
     pub async fn ping(&mut self) -> anyhow::Result<()> {
-        todo!()
+        self.message_buf.clear();
+        serde_json::to_writer(&mut self.message_buf, &BlankConnectionMessage::Ping).context("writing json")?;
+        self.message_buf.push(b'\n');
+
+        self.stream.write_all(&self.message_buf).await.context("Sending ping")?;
+        self.stream.flush().await.context("Flushing ping")?;
+        Ok(())
     }
 }
 
 pub struct EstablishedConnection<S>(S);
 
 impl EstablishedConnection<BufReader<TcpStream>> {
-    pub async fn connect(addr: impl ToSocketAddrs, req: &Request<'_>) -> anyhow::Result<Self> {
-        todo!()
+    pub async fn connect(addr: impl ToSocketAddrs, req: Request<'_>) -> anyhow::Result<Self> {
+        BlankConnection::connect(addr).await?.request(req).await
+    }
+}
+
+impl EstablishedConnection<BufReader<UnixStream>> {
+    pub async fn connect_unix(path: impl AsRef<std::path::Path>, req: Request<'_>) -> anyhow::Result<Self> {
+        BlankConnection::connect_unix(path).await?.request(req).await
+    }
+}
+
+impl EstablishedConnection<BufReader<TlsStream<TcpStream>>> {
+    pub async fn connect_tls(
+        addr: impl ToSocketAddrs,
+        server_name: ServerName<'static>,
+        config: ClientConfig,
+        req: Request<'_>,
+    ) -> anyhow::Result<Self> {
+        BlankConnection::connect_tls(addr, server_name, config).await?.request(req).await
+    }
+}
+
+impl EstablishedConnection<BufReader<WsStream<TcpStream>>> {
+    pub async fn connect_ws(addr: impl ToSocketAddrs, host: &str, path: &str, req: Request<'_>) -> anyhow::Result<Self> {
+        BlankConnection::connect_ws(addr, host, path).await?.request(req).await
     }
 }
 
 impl<S> EstablishedConnection<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self(stream)
+    }
+
     pub fn inner(self) -> S {
         self.0
     }
 }
-
@@ -2,6 +2,12 @@ use std::borrow::Cow;
 use serde::{Serialize, Deserialize};
 
 pub mod client;
+pub mod quic;
+pub mod ws;
+
+/// ALPN protocol id used to negotiate a tcpman tunnel on a TLS connection,
+/// so a server can tell tunnel clients apart from other traffic on the same port.
+pub const ALPN_PROTOCOL: &[u8] = b"tcpman/1";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request<'a> {
@@ -19,7 +25,7 @@ pub enum Request<'a> {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-enum BlankConnectionMessage<'a> {
+pub enum BlankConnectionMessage<'a> {
     Ping,
     Connect(Request<'a>),
-}
\ No newline at end of file
+}
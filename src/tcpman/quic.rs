@@ -0,0 +1,104 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{bail, Context as _};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader, ReadBuf};
+
+use super::client::EstablishedConnection;
+use super::{BlankConnectionMessage, Request};
+
+/// How often an idle QUIC connection sends a keep-alive frame, on both the client
+/// ([`QuicConnection::connect`]) and server (`load_quic_server_config` in `main.rs`) side, so
+/// the connection survives NAT/firewall idle timeouts between proxied requests.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A single QUIC connection to a tcpman server. Unlike the TCP-based transports, many
+/// proxied requests share this one congestion-controlled connection:
+/// each [`QuicConnection::request`] call opens its own bidirectional stream instead of
+/// dialing a new connection, so independent proxied flows no longer head-of-line block
+/// each other, and the tunnel survives client IP/port changes via connection migration.
+/// [`QuicConnection::connect`] always completes a full handshake; 0-RTT is not used.
+pub struct QuicConnection {
+    connection: quinn::Connection,
+}
+
+impl QuicConnection {
+    pub async fn connect(addr: SocketAddr, server_name: &str, mut config: quinn::ClientConfig) -> anyhow::Result<Self> {
+        let mut transport = quinn::TransportConfig::default();
+        transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+        config.transport_config(Arc::new(transport));
+
+        let mut endpoint = quinn::Endpoint::client("[::]:0".parse().unwrap()).context("Creating QUIC endpoint")?;
+        endpoint.set_default_client_config(config);
+
+        let connection = endpoint.connect(addr, server_name).context("Starting QUIC connection")?
+            .await
+            .context("Establishing QUIC connection")?;
+
+        Ok(Self { connection })
+    }
+
+    /// Opens a new bidirectional QUIC stream and writes the `Connect` header to it, the
+    /// same way a fresh TCP/TLS/WebSocket connection would, but without paying for a new
+    /// handshake.
+    pub async fn request(&self, req: Request<'_>) -> anyhow::Result<EstablishedConnection<BufReader<QuicStream>>> {
+        let (mut send, recv) = self.connection.open_bi().await.context("Opening QUIC stream")?;
+
+        let mut header = Vec::new();
+        serde_json::to_writer(&mut header, &BlankConnectionMessage::Connect(req)).context("writing json")?;
+        header.push(b'\n');
+
+        use tokio::io::AsyncWriteExt;
+        send.write_all(&header).await.context("Writing connect header")?;
+        send.flush().await.context("Flushing connect header")?;
+
+        Ok(EstablishedConnection::new(BufReader::new(QuicStream { send, recv })))
+    }
+
+    /// QUIC keep-alive frames (configured via [`KEEP_ALIVE_INTERVAL`] on both the client and
+    /// server transport config) are sent transparently by the transport; this just surfaces
+    /// whether the connection is still open.
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        if let Some(reason) = self.connection.close_reason() {
+            bail!("QUIC connection closed: {reason}");
+        }
+        Ok(())
+    }
+}
+
+/// Adapts a QUIC bidirectional stream's separate send/receive halves into a single
+/// `AsyncRead + AsyncWrite` byte stream.
+pub struct QuicStream {
+    send: quinn::SendStream,
+    recv: quinn::RecvStream,
+}
+
+impl QuicStream {
+    pub(crate) fn new(send: quinn::SendStream, recv: quinn::RecvStream) -> Self {
+        Self { send, recv }
+    }
+}
+
+impl AsyncRead for QuicStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.send).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.send).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+use bytes::{Buf, BytesMut};
+use futures_util::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Adapts a WebSocket connection carrying binary frames into a plain
+/// `AsyncRead + AsyncWrite` byte stream, so it plugs into `BlankConnection<S>` /
+/// `EstablishedConnection<S>` without the request logic having to know about
+/// framing at all: outbound writes become binary frames and inbound frames are
+/// concatenated back into a byte stream. WebSocket-level Ping frames are answered
+/// with Pong automatically, independent of the `BlankConnectionMessage::Ping`
+/// keepalive the tunnel protocol itself uses.
+pub struct WsStream<S> {
+    inner: WebSocketStream<S>,
+    read_buf: BytesMut,
+}
+
+impl<S> WsStream<S> {
+    pub(crate) fn new(inner: WebSocketStream<S>) -> Self {
+        Self { inner, read_buf: BytesMut::new() }
+    }
+}
+
+impl<S> AsyncRead for WsStream<S>
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if !self.read_buf.is_empty() {
+                let n = buf.remaining().min(self.read_buf.len());
+                buf.put_slice(&self.read_buf[..n]);
+                self.read_buf.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Binary(data)))) => self.read_buf.extend_from_slice(&data),
+
+                Poll::Ready(Some(Ok(Message::Ping(data)))) => {
+                    // Best-effort Pong reply; if the sink isn't ready we simply skip it,
+                    // the peer will retry its own ping on the next keepalive tick.
+                    let _ = Pin::new(&mut self.inner).poll_ready(cx)
+                        .map(|r| r.and_then(|()| Pin::new(&mut self.inner).start_send(Message::Pong(data))));
+                }
+
+                Poll::Ready(Some(Ok(Message::Close(_)))) | Poll::Ready(None) => return Poll::Ready(Ok(())),
+
+                // Control/text frames that aren't tunnel payload.
+                Poll::Ready(Some(Ok(_))) => continue,
+
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WsStream<S>
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.inner).poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}